@@ -0,0 +1,266 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use clap::{App, Arg};
+use tui::style::Color;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TemperatureType {
+    Celsius,
+    Fahrenheit,
+    Kelvin,
+}
+
+impl TemperatureType {
+    pub fn convert(self, celsius: f64) -> f64 {
+        match self {
+            TemperatureType::Celsius => celsius,
+            TemperatureType::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+            TemperatureType::Kelvin => celsius + 273.15,
+        }
+    }
+
+    // Kelvin is never written with a degree sign ("300 K", not "300 °K").
+    pub fn symbol(self) -> &'static str {
+        match self {
+            TemperatureType::Celsius => "°C",
+            TemperatureType::Fahrenheit => "°F",
+            TemperatureType::Kelvin => "K",
+        }
+    }
+
+    pub fn cycle(self) -> TemperatureType {
+        match self {
+            TemperatureType::Celsius => TemperatureType::Fahrenheit,
+            TemperatureType::Fahrenheit => TemperatureType::Kelvin,
+            TemperatureType::Kelvin => TemperatureType::Celsius,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ViewMode {
+    Bars,
+    Graph,
+    Basic,
+}
+
+impl ViewMode {
+    pub fn cycle(self) -> ViewMode {
+        match self {
+            ViewMode::Bars => ViewMode::Graph,
+            ViewMode::Graph => ViewMode::Basic,
+            ViewMode::Basic => ViewMode::Bars,
+        }
+    }
+}
+
+/// Per-group settings: whether the group is rendered, its default max
+/// threshold (used until a real sensor maximum is known), its color, and the
+/// warning/critical levels a sensor's value switches color at.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GroupSettings {
+    pub visible: bool,
+    pub max: u64,
+    pub color: String,
+    pub warning: u64,
+    pub critical: u64,
+}
+
+impl GroupSettings {
+    fn new(max: u64, color: &str) -> GroupSettings {
+        GroupSettings {
+            visible: true,
+            max: max,
+            color: color.to_owned(),
+            warning: max * 9 / 10,
+            critical: max,
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        color_from_name(&self.color)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Settings {
+    pub poll_ms: u64,
+    pub unit: TemperatureType,
+    pub view: ViewMode,
+    pub cpu: GroupSettings,
+    pub gpu: GroupSettings,
+    pub hdd: GroupSettings,
+    pub fan: GroupSettings,
+    pub other: GroupSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            poll_ms: 500,
+            unit: TemperatureType::Celsius,
+            view: ViewMode::Bars,
+            cpu: GroupSettings::new(80, "green"),
+            gpu: GroupSettings::new(90, "yellow"),
+            hdd: GroupSettings::new(60, "cyan"),
+            fan: GroupSettings::new(4000, "magenta"),
+            other: GroupSettings::new(80, "white"),
+        }
+    }
+}
+
+fn color_from_name(name: &str) -> Color {
+    match name.to_lowercase().as_str() {
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "white" => Color::White,
+        "gray" | "grey" => Color::Gray,
+        _ => Color::White,
+    }
+}
+
+fn default_config_path() -> PathBuf {
+    PathBuf::from("tsensor.toml")
+}
+
+fn read_or_create(path: &Path) -> Settings {
+    match fs::read_to_string(path) {
+        Ok(contents) => toml::from_str(&contents).unwrap_or_else(|_| Settings::default()),
+        Err(_) => {
+            let settings = Settings::default();
+            if let Ok(contents) = toml::to_string_pretty(&settings) {
+                if let Ok(mut file) = fs::File::create(path) {
+                    let _ = file.write_all(contents.as_bytes());
+                }
+            }
+            settings
+        }
+    }
+}
+
+/// Parse CLI arguments, load (or create) the TOML config file they point
+/// to, and apply any CLI overrides on top of it.
+pub fn load() -> Settings {
+    let matches = App::new("tsensor")
+        .version(crate_version!())
+        .about("A terminal sensor monitor")
+        .arg(Arg::with_name("config")
+                 .short("c")
+                 .long("config")
+                 .value_name("FILE")
+                 .help("Path to the TOML config file (created with defaults if missing)")
+                 .takes_value(true))
+        .arg(Arg::with_name("interval")
+                 .short("i")
+                 .long("interval")
+                 .value_name("MS")
+                 .help("Sensor poll interval, in milliseconds")
+                 .takes_value(true))
+        .arg(Arg::with_name("unit")
+                 .short("u")
+                 .long("unit")
+                 .value_name("UNIT")
+                 .help("Temperature unit: celsius, fahrenheit or kelvin")
+                 .takes_value(true))
+        .arg(Arg::with_name("view")
+                 .long("view")
+                 .value_name("MODE")
+                 .help("Display mode: bars, graph or basic")
+                 .takes_value(true))
+        .arg(Arg::with_name("basic")
+                 .short("b")
+                 .long("basic")
+                 .help("Shorthand for --view basic, for small terminals or headless logging"))
+        .get_matches();
+
+    let config_path = matches
+        .value_of("config")
+        .map(PathBuf::from)
+        .unwrap_or_else(default_config_path);
+    let mut settings = read_or_create(&config_path);
+
+    if let Some(interval) = matches.value_of("interval") {
+        if let Ok(ms) = interval.parse() {
+            settings.poll_ms = ms;
+        }
+    }
+    if let Some(unit) = matches.value_of("unit") {
+        settings.unit = match unit.to_lowercase().as_str() {
+            "f" | "fahrenheit" => TemperatureType::Fahrenheit,
+            "k" | "kelvin" => TemperatureType::Kelvin,
+            _ => TemperatureType::Celsius,
+        };
+    }
+    if let Some(view) = matches.value_of("view") {
+        settings.view = match view.to_lowercase().as_str() {
+            "graph" => ViewMode::Graph,
+            "basic" => ViewMode::Basic,
+            _ => ViewMode::Bars,
+        };
+    }
+    if matches.is_present("basic") {
+        settings.view = ViewMode::Basic;
+    }
+
+    settings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_applies_the_right_formula() {
+        assert_eq!(TemperatureType::Celsius.convert(100.0), 100.0);
+        assert_eq!(TemperatureType::Fahrenheit.convert(100.0), 212.0);
+        assert_eq!(TemperatureType::Kelvin.convert(0.0), 273.15);
+    }
+
+    #[test]
+    fn cycle_wraps_back_to_celsius() {
+        assert_eq!(TemperatureType::Celsius.cycle(), TemperatureType::Fahrenheit);
+        assert_eq!(TemperatureType::Fahrenheit.cycle(), TemperatureType::Kelvin);
+        assert_eq!(TemperatureType::Kelvin.cycle(), TemperatureType::Celsius);
+    }
+
+    #[test]
+    fn group_settings_new_derives_warning_and_critical_from_max() {
+        let settings = GroupSettings::new(80, "green");
+        assert_eq!(settings.warning, 72);
+        assert_eq!(settings.critical, 80);
+    }
+
+    #[test]
+    fn color_from_name_matches_known_names_case_insensitively() {
+        assert_eq!(color_from_name("Green"), Color::Green);
+        assert_eq!(color_from_name("GRAY"), Color::Gray);
+        assert_eq!(color_from_name("grey"), Color::Gray);
+    }
+
+    #[test]
+    fn color_from_name_falls_back_to_white() {
+        assert_eq!(color_from_name("not-a-color"), Color::White);
+    }
+
+    #[test]
+    fn read_or_create_falls_back_to_defaults_on_malformed_toml() {
+        let mut path = std::env::temp_dir();
+        path.push("tsensor-test-malformed.toml");
+        fs::File::create(&path)
+            .unwrap()
+            .write_all(b"not valid toml {{{")
+            .unwrap();
+
+        let settings = read_or_create(&path);
+        assert_eq!(settings.poll_ms, Settings::default().poll_ms);
+
+        let _ = fs::remove_file(&path);
+    }
+}
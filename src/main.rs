@@ -3,49 +3,143 @@ extern crate termion;
 extern crate futures;
 extern crate tokio_core;
 extern crate libpsensor;
+#[macro_use]
+extern crate clap;
+extern crate toml;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+mod config;
 
 use std::io;
 use std::thread;
 use std::time;
-use std::sync::{mpsc, Arc};
+use std::sync::{mpsc, Arc, Mutex};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::collections::VecDeque;
 
 use termion::event;
 use termion::input::TermRead;
 use tui::Terminal;
 use tui::backend::TermionBackend;
-use tui::widgets::{Widget, Block, border, BarChart};
+use tui::widgets::{Widget, Block, border, BarChart, Chart, Axis, Dataset, Marker, Paragraph};
 use tui::layout::{Group, Direction, Size, Rect};
-use tui::style::{Style, Color};
+use tui::style::{Style, Color, Modifier};
 use futures::Stream;
 use tokio_core::reactor::Core;
 use libpsensor::{Psensor, PsensorType};
 
+use config::{Settings, TemperatureType, ViewMode};
+
+// Number of samples kept per sensor for the graph view.
+const HISTORY_LEN: usize = 120;
+
+const HELP_TEXT: &'static str = "\
+q          quit
+g          cycle bar/graph/basic view
+u          cycle temperature unit
+f          freeze/unfreeze the display
+r, C-r     clear history
+?          toggle this help
+Esc        close this help
+";
+
+type SensorEntry = (Arc<Psensor>, Arc<AtomicUsize>, Arc<Mutex<VecDeque<(f64, f64)>>>);
+
+fn duration_to_secs(d: time::Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 / 1_000_000_000.0
+}
+
+// How close a sensor's value is to its configured warning/critical levels.
+#[derive(Clone, Copy, PartialEq)]
+enum Severity {
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    fn classify(value: u64, warning: u64, critical: u64) -> Severity {
+        if value >= critical {
+            Severity::Critical
+        } else if value >= warning {
+            Severity::Warning
+        } else {
+            Severity::Normal
+        }
+    }
+
+    // The group's own color for `Normal`, otherwise a fixed alert color.
+    fn color(self, base: Color) -> Color {
+        match self {
+            Severity::Normal => base,
+            Severity::Warning => Color::Yellow,
+            Severity::Critical => Color::Red,
+        }
+    }
+
+    fn style(self, base: Color) -> Style {
+        let style = Style::default().fg(self.color(base));
+        if self == Severity::Critical {
+            style.modifier(Modifier::Blink)
+        } else {
+            style
+        }
+    }
+}
+
 struct App {
     size: Rect,
-    data: Vec<(Arc<libpsensor::Psensor>, Arc<AtomicUsize>)>,
+    data: Vec<SensorEntry>,
+    start: time::Instant,
+    view: ViewMode,
+    unit: TemperatureType,
+    settings: Settings,
+    frozen: bool,
+    snapshot: Option<Vec<Panel>>,
+    help: bool,
 }
 
 impl App {
-    fn new() -> App {
+    fn new(settings: Settings) -> App {
         let (tx, rx) = mpsc::sync_channel(1);
         thread::spawn(move || {
+            let start = time::Instant::now();
             let mut lp = Core::new().unwrap();
-            let (sensors, stream) = libpsensor::new(time::Duration::from_millis(500), &lp.handle());
+            let (sensors, stream) =
+                libpsensor::new(time::Duration::from_millis(settings.poll_ms), &lp.handle());
             let data = sensors
                 .into_iter()
-                .map(|sensor| (sensor, Arc::new(AtomicUsize::new(1))))
+                .map(|sensor| {
+                         (sensor,
+                          Arc::new(AtomicUsize::new(1)),
+                          Arc::new(Mutex::new(VecDeque::with_capacity(HISTORY_LEN))))
+                     })
                 .collect::<Vec<_>>();
 
+            let unit = settings.unit;
             let app = App {
                 size: Rect::default(),
                 data: data.clone(),
+                start: start,
+                view: settings.view,
+                unit: unit,
+                settings: settings,
+                frozen: false,
+                snapshot: None,
+                help: false,
             };
             tx.send(app).unwrap();
             lp.run(stream.for_each(move |(sensor, new_value)| {
-                    for &(ref s, ref value) in &data {
+                    for &(ref s, ref value, ref history) in &data {
                         if sensor.id == s.id {
                             value.store(new_value as usize, Ordering::Relaxed);
+                            let mut history = history.lock().unwrap();
+                            if history.len() >= HISTORY_LEN {
+                                history.pop_front();
+                            }
+                            history.push_back((duration_to_secs(start.elapsed()), new_value));
                             break;
                         }
                     }
@@ -63,6 +157,8 @@ enum Event {
 }
 
 fn main() {
+    let settings = config::load();
+
     // Terminal initialization
     let backend = TermionBackend::new().unwrap();
     let mut terminal = Terminal::new(backend).unwrap();
@@ -85,13 +181,14 @@ fn main() {
     });
 
     // Tick
+    let poll_ms = settings.poll_ms;
     thread::spawn(move || loop {
                       clock_tx.send(Event::Tick).unwrap();
-                      thread::sleep(time::Duration::from_millis(500));
+                      thread::sleep(time::Duration::from_millis(poll_ms));
                   });
 
     // App
-    let mut app = App::new();
+    let mut app = App::new(settings);
 
     // First draw call
     terminal.clear().unwrap();
@@ -109,8 +206,28 @@ fn main() {
         let evt = rx.recv().unwrap();
         match evt {
             Event::Input(input) => {
-                if input == event::Key::Char('q') {
-                    break;
+                match input {
+                    event::Key::Char('q') => break,
+                    event::Key::Char('g') => app.view = app.view.cycle(),
+                    event::Key::Char('u') => app.unit = app.unit.cycle(),
+                    event::Key::Char('f') => {
+                        if app.frozen {
+                            app.frozen = false;
+                            app.snapshot = None;
+                        } else {
+                            app.snapshot = Some(build_panels(&app));
+                            app.frozen = true;
+                        }
+                    }
+                    event::Key::Char('r') |
+                    event::Key::Ctrl('r') => {
+                        for &(_, _, ref history) in &app.data {
+                            history.lock().unwrap().clear();
+                        }
+                    }
+                    event::Key::Char('?') => app.help = !app.help,
+                    event::Key::Esc => app.help = false,
+                    _ => {}
                 }
             }
             Event::Tick => {}
@@ -121,92 +238,312 @@ fn main() {
     terminal.show_cursor().unwrap();
 }
 
-fn filter_sensor(sensors: &[(Arc<Psensor>, Arc<AtomicUsize>)],
+fn filter_sensor(sensors: &[SensorEntry],
                  sensor_type: PsensorType,
-                 default_max: u64)
-                 -> (Vec<(&str, u64)>, u64) {
+                 default_max: u64,
+                 warning: u64,
+                 critical: u64,
+                 unit: Option<TemperatureType>)
+                 -> (Vec<(String, u64)>, Vec<Severity>, u64) {
+    let (warning, critical) = match unit {
+        Some(unit) => (unit.convert(warning as f64).round() as u64, unit.convert(critical as f64).round() as u64),
+        None => (warning, critical),
+    };
+    let default_max = match unit {
+        Some(unit) => unit.convert(default_max as f64).round() as u64,
+        None => default_max,
+    };
     let tmp = sensors
         .iter()
-        .filter_map(|&(ref sensor, ref value)| if sensor.sensor == sensor_type {
-                        Some((sensor.max,
-                              (sensor.name.as_str(), value.load(Ordering::Relaxed) as u64)))
+        .filter_map(|&(ref sensor, ref value, _)| if sensor.sensor == sensor_type {
+                        let raw = value.load(Ordering::Relaxed) as f64;
+                        let (max, value) = match unit {
+                            Some(unit) => (unit.convert(sensor.max), unit.convert(raw)),
+                            None => (sensor.max, raw),
+                        };
+                        let value = value.round() as u64;
+                        Some((max, (sensor.name.clone(), value), Severity::classify(value, warning, critical)))
                     } else {
                         None
                     })
         .collect::<Vec<_>>();
-    let cpus_max_temp = tmp.clone()
+    let cpus_max_temp = tmp.iter()
+        .map(|&(max, _, _)| max)
+        .filter(|max| !max.is_nan())
+        .map(|max| max as u64)
+        .max()
+        .unwrap_or(default_max);
+    let (bars, severities) = tmp.into_iter().map(|(_, bar, severity)| (bar, severity)).unzip();
+    (bars, severities, cpus_max_temp)
+}
+
+// Like `filter_sensor`, but returns each sensor's history of `(offset, value)`
+// samples, where `offset` is the sample time minus `now` in seconds (zero or
+// negative), instead of its instantaneous value, for use with the graph view. The
+// severity returned per sensor reflects its most recent sample.
+fn filter_sensor_history(sensors: &[SensorEntry],
+                          sensor_type: PsensorType,
+                          now: f64,
+                          default_max: u64,
+                          warning: u64,
+                          critical: u64,
+                          unit: Option<TemperatureType>)
+                          -> (Vec<(String, Vec<(f64, f64)>)>, Vec<Severity>, u64) {
+    let (warning, critical) = match unit {
+        Some(unit) => (unit.convert(warning as f64).round() as u64, unit.convert(critical as f64).round() as u64),
+        None => (warning, critical),
+    };
+    let default_max = match unit {
+        Some(unit) => unit.convert(default_max as f64).round() as u64,
+        None => default_max,
+    };
+    let tmp = sensors
         .iter()
-        .map(|&(max, _)| max)
+        .filter_map(|&(ref sensor, _, ref history)| if sensor.sensor == sensor_type {
+                        let history = history.lock().unwrap();
+                        let points = history
+                            .iter()
+                            .map(|&(t, v)| {
+                                     (t - now, unit.map_or(v, |unit| unit.convert(v)))
+                                 })
+                            .collect::<Vec<_>>();
+                        let max = unit.map_or(sensor.max, |unit| unit.convert(sensor.max));
+                        let severity = points
+                            .last()
+                            .map_or(Severity::Normal,
+                                    |&(_, v)| Severity::classify(v.round() as u64, warning, critical));
+                        Some((max, (sensor.name.clone(), points), severity))
+                    } else {
+                        None
+                    })
+        .collect::<Vec<_>>();
+    let max = tmp.iter()
+        .map(|&(max, _, _)| max)
         .filter(|max| !max.is_nan())
         .map(|max| max as u64)
         .max()
         .unwrap_or(default_max);
-    let r = tmp.into_iter().map(|(_, v)| v).collect();
-    (r, cpus_max_temp)
+    let (history, severities) = tmp.into_iter().map(|(_, h, severity)| (h, severity)).unzip();
+    (history, severities, max)
 }
 
-fn draw(t: &mut Terminal<TermionBackend>, app: &App) {
-    let (cpus, cpus_max_temp) = filter_sensor(&app.data, PsensorType::Cpu, 80);
-    let (gpus, gpus_max_temp) = filter_sensor(&app.data, PsensorType::Gpu, 90);
-    let (hdds, hdds_max_temp) = filter_sensor(&app.data, PsensorType::Hdd, 60);
-    let (fans, fans_max_temp) = filter_sensor(&app.data, PsensorType::Fan, 4000);
-    let (others, others_max_temp) = filter_sensor(&app.data, PsensorType::Other(true), 80);
+// A single group's data, ready to be rendered into whichever grid cell it
+// ends up in; built in `build_panels` and laid out by `layout_panels`. Owns
+// its strings so a frozen snapshot can outlive the `App::data` borrow it was
+// built from.
+#[derive(Clone)]
+struct Panel {
+    title: String,
+    color: Color,
+    unit: String,
+    bars: Vec<(String, u64)>,
+    bar_severities: Vec<Severity>,
+    bars_max: u64,
+    history: Vec<(String, Vec<(f64, f64)>)>,
+    history_severities: Vec<Severity>,
+    history_max: u64,
+    history_span: f64,
+}
+
+// `BarChart` takes one style for its whole chart, so the bar view renders one
+// sub-chart per sensor, each with its own alert color. The graph view draws a
+// single `Chart` whose per-sensor `Dataset`s each carry their own alert style.
+fn render_group(t: &mut Terminal<TermionBackend>, area: &Rect, panel: &Panel, view: ViewMode) {
+    Block::default().title(&panel.title).borders(border::ALL).render(t, area);
+    let inner = Rect {
+        x: area.x + 1,
+        y: area.y + 1,
+        width: area.width.saturating_sub(2),
+        height: area.height.saturating_sub(2),
+    };
+
+    match view {
+        ViewMode::Bars => {
+            if panel.bars.is_empty() {
+                return;
+            }
+            let col_size = 100 / panel.bars.len() as u16;
+            let col_sizes = vec![Size::Percent(col_size); panel.bars.len()];
+            Group::default()
+                .direction(Direction::Horizontal)
+                .sizes(&col_sizes)
+                .render(t, &inner, |t, col_chunks| {
+                    let bars = panel.bars.iter().zip(panel.bar_severities.iter());
+                    for ((&(ref name, value), &severity), col_chunk) in bars.zip(col_chunks.iter()) {
+                        let style = severity.style(panel.color);
+                        BarChart::default()
+                            .max(panel.bars_max)
+                            .data(&[(name.as_str(), value)])
+                            .bar_width(9)
+                            .style(style)
+                            .value_style(Style::default().fg(Color::Black).bg(severity.color(panel.color)))
+                            .render(t, col_chunk);
+                    }
+                });
+        }
+        ViewMode::Graph => {
+            let datasets = panel
+                .history
+                .iter()
+                .zip(panel.history_severities.iter())
+                .map(|(&(ref name, ref data), &severity)| {
+                         Dataset::default()
+                             .name(name)
+                             .marker(Marker::Braille)
+                             .style(severity.style(panel.color))
+                             .data(data)
+                     })
+                .collect::<Vec<_>>();
+            let low_label = format!("-{}", panel.history_span as u64);
+            let high_label = panel.history_max.to_string();
+            Chart::default()
+                .x_axis(Axis::default()
+                            .style(Style::default().fg(Color::Gray))
+                            .bounds([-panel.history_span, 0.0])
+                            .labels(&[&low_label, "0"]))
+                .y_axis(Axis::default()
+                            .style(Style::default().fg(Color::Gray))
+                            .bounds([0.0, panel.history_max as f64])
+                            .labels(&["0", &high_label]))
+                .datasets(&datasets)
+                .render(t, &inner);
+        }
+        ViewMode::Basic => {
+            let lines = panel
+                .bars
+                .iter()
+                .map(|&(ref name, value)| {
+                         format!("{:<12} {:>5}{}  (max {})\n",
+                                 name,
+                                 value,
+                                 panel.unit,
+                                 panel.bars_max)
+                     })
+                .collect::<String>();
+            Paragraph::default().wrap(false).text(&lines).render(t, &inner);
+        }
+    }
+}
+
+// Arrange the visible panels into a responsive grid (at most 3 columns per
+// row) instead of the old fixed five-slot layout, so hidden groups don't
+// leave blank space.
+fn layout_panels(t: &mut Terminal<TermionBackend>, area: &Rect, panels: &[Panel], view: ViewMode) {
+    if panels.is_empty() {
+        return;
+    }
+
+    let rows = panels.chunks(3).collect::<Vec<_>>();
+    let row_size = 100 / rows.len() as u16;
+    let row_sizes = vec![Size::Percent(row_size); rows.len()];
     Group::default()
         .direction(Direction::Vertical)
         .margin(1)
-        .sizes(&[Size::Percent(60), Size::Percent(40)])
-        .render(t, &app.size, |t, chunks| {
-            Group::default()
-                .direction(Direction::Horizontal)
-                .sizes(&[Size::Percent(33), Size::Percent(33), Size::Percent(33)])
-                .render(t, &chunks[0], |t, chunks| {
-                    BarChart::default()
-                        .block(Block::default().title("CPUs").borders(border::ALL))
-                        .max(cpus_max_temp)
-                        .data(&cpus)
-                        .bar_width(9)
-                        .style(Style::default().fg(Color::Green))
-                        .value_style(Style::default().fg(Color::Black).bg(Color::Green))
-                        .render(t, &chunks[0]);
-                    BarChart::default()
-                        .block(Block::default().title("GPUs").borders(border::ALL))
-                        .max(gpus_max_temp)
-                        .data(&gpus)
-                        .bar_width(9)
-                        .style(Style::default().fg(Color::Yellow))
-                        .value_style(Style::default().fg(Color::Black).bg(Color::Yellow))
-                        .render(t, &chunks[1]);
-                    BarChart::default()
-                        .block(Block::default().title("HDDs").borders(border::ALL))
-                        .max(hdds_max_temp)
-                        .data(&hdds)
-                        .bar_width(9)
-                        .style(Style::default().fg(Color::Cyan))
-                        .value_style(Style::default().fg(Color::Black).bg(Color::Cyan))
-                        .render(t, &chunks[2]);
+        .sizes(&row_sizes)
+        .render(t, area, |t, row_chunks| for (row, row_chunk) in rows.iter().zip(row_chunks) {
+                    let col_size = 100 / row.len() as u16;
+                    let col_sizes = vec![Size::Percent(col_size); row.len()];
+                    Group::default()
+                        .direction(Direction::Horizontal)
+                        .sizes(&col_sizes)
+                        .render(t, row_chunk, |t, col_chunks| {
+                        for (panel, col_chunk) in row.iter().zip(col_chunks) {
+                            render_group(t, col_chunk, panel, view);
+                        }
+                    });
                 });
+}
+
+// Computes the current set of visible panels from the live sensor data.
+// Called on every draw while running normally, and once more when `f`
+// freezes the display to capture the snapshot `draw` then keeps reusing.
+fn build_panels(app: &App) -> Vec<Panel> {
+    let unit = Some(app.unit);
+    let history_span = HISTORY_LEN as f64 * app.settings.poll_ms as f64 / 1000.0;
+    let now = duration_to_secs(app.start.elapsed());
+
+    // (sensor type, its settings, display name, whether it's a temperature
+    // group that should be unit-converted). Fan is the only non-temperature
+    // group today.
+    let groups = [(PsensorType::Cpu, &app.settings.cpu, "CPUs", true),
+                  (PsensorType::Gpu, &app.settings.gpu, "GPUs", true),
+                  (PsensorType::Hdd, &app.settings.hdd, "HDDs", true),
+                  (PsensorType::Fan, &app.settings.fan, "Fans", false),
+                  (PsensorType::Other(true), &app.settings.other, "Others", true)];
+
+    let mut panels = Vec::new();
+    for &(sensor_type, settings, name, is_temperature) in &groups {
+        if !settings.visible {
+            continue;
+        }
+
+        let unit = if is_temperature { unit } else { None };
+        let (title, unit_str) = if is_temperature {
+            (format!("{} ({})", name, app.unit.symbol()), app.unit.symbol().to_owned())
+        } else {
+            (name.to_owned(), " RPM".to_owned())
+        };
+
+        let (bars, bar_severities, bars_max) = filter_sensor(&app.data,
+                                                              sensor_type,
+                                                              settings.max,
+                                                              settings.warning,
+                                                              settings.critical,
+                                                              unit);
+        let (history, history_severities, history_max) = filter_sensor_history(&app.data,
+                                                                                 sensor_type,
+                                                                                 now,
+                                                                                 settings.max,
+                                                                                 settings.warning,
+                                                                                 settings.critical,
+                                                                                 unit);
+        panels.push(Panel {
+                        title: title,
+                        unit: unit_str,
+                        color: settings.color(),
+                        bars: bars,
+                        bar_severities: bar_severities,
+                        bars_max: bars_max,
+                        history: history,
+                        history_severities: history_severities,
+                        history_max: history_max,
+                        history_span: history_span,
+                    });
+    }
+
+    panels
+}
+
+fn render_help(t: &mut Terminal<TermionBackend>, area: &Rect) {
+    Group::default()
+        .direction(Direction::Vertical)
+        .sizes(&[Size::Percent(30), Size::Percent(40), Size::Percent(30)])
+        .render(t, area, |t, v_chunks| {
             Group::default()
                 .direction(Direction::Horizontal)
-                .sizes(&[Size::Percent(50), Size::Percent(50)])
-                .render(t, &chunks[1], |t, chunks| {
-                    BarChart::default()
-                        .block(Block::default().title("Fans").borders(border::ALL))
-                        .max(fans_max_temp)
-                        .data(&fans)
-                        .bar_width(9)
-                        .style(Style::default().fg(Color::Magenta))
-                        .value_style(Style::default().fg(Color::Black).bg(Color::Magenta))
-                        .render(t, &chunks[0]);
-                    BarChart::default()
-                        .block(Block::default().title("Others").borders(border::ALL))
-                        .max(others_max_temp)
-                        .data(&others)
-                        .bar_width(9)
-                        .style(Style::default().fg(Color::White))
-                        .value_style(Style::default().fg(Color::Black).bg(Color::White))
-                        .render(t, &chunks[1]);
+                .sizes(&[Size::Percent(20), Size::Percent(60), Size::Percent(20)])
+                .render(t, &v_chunks[1], |t, h_chunks| {
+                    Paragraph::default()
+                        .block(Block::default().title("Help").borders(border::ALL))
+                        .wrap(true)
+                        .text(HELP_TEXT)
+                        .render(t, &h_chunks[1]);
                 });
         });
+}
+
+fn draw(t: &mut Terminal<TermionBackend>, app: &App) {
+    let panels = if app.frozen {
+        app.snapshot.clone().unwrap_or_else(|| build_panels(app))
+    } else {
+        build_panels(app)
+    };
+
+    layout_panels(t, &app.size, &panels, app.view);
+
+    if app.help {
+        render_help(t, &app.size);
+    }
 
     t.draw().unwrap();
 }